@@ -1,40 +1,388 @@
-use lighthouse_network::Enr;
+use alloy_rlp::{Decodable, Encodable};
+use lighthouse_network::{discv5::enr::NodeId, Enr, Eth2Enr};
+use slog::{debug, warn, Logger};
+use std::collections::HashMap;
 use std::sync::Arc;
-use store::{DBColumn, Error as StoreError, HotColdDB, ItemStore, StoreItem};
+use store::{DBColumn, Error as StoreError, HotColdDB, ItemStore, StoreItem, StoreOp};
 use types::{EthSpec, Hash256};
 
-/// 32-byte key for accessing the `DhtEnrs`. All zero because `DhtEnrs` has its own column.
+/// 32-byte key for accessing the legacy monolithic `PersistedDht` blob. All zero because
+/// `DhtEnrs` has its own column. Kept around so nodes upgrading from an older database can still
+/// find their ENR set; `load_dht` migrates it to the keyed layout below on first load.
 pub const DHT_DB_KEY: Hash256 = Hash256::ZERO;
 
+/// Sentinel key holding the monotonically increasing write counter used to stamp each
+/// `PersistedEnr` with a `last_seen` value for LRU-style pruning. ENRs carry no wall-clock
+/// timestamp of their own, so this plays the same role a slot number plays for state pruning.
+fn dht_seq_db_key() -> Hash256 {
+    Hash256::from([0xff; 32])
+}
+
+/// Derives the per-node `DhtEnrs` key an `Enr` is stored under, from its node id.
+fn node_id_key(enr: &Enr) -> Hash256 {
+    Hash256::from_slice(&enr.node_id().raw())
+}
+
+/// Replaces `existing` with `candidate` if `candidate` advertises a higher ENR `seq`, the same
+/// tie-break `prune_dht`'s scan uses when it finds more than one record for a node id.
+fn keep_highest_seq(existing: &mut Enr, candidate: Enr) {
+    if candidate.seq() > existing.seq() {
+        *existing = candidate;
+    }
+}
+
+/// Collapses `enrs` down to one entry per node id, keeping the entry with the highest ENR `seq`
+/// when duplicates exist. `DhtEnrs` itself can never hold two records for the same node id (they
+/// share a key), but the legacy monolithic blob predates that guarantee, so a batch built from it
+/// can still contain duplicates; without deduping first, a write built from it would emit one
+/// `StoreOp::KeyValueOp` per duplicate at the same key and whichever happened to be ordered last
+/// in the `Vec` would win, not necessarily the highest-`seq` one.
+fn dedupe_by_highest_seq(enrs: Vec<Enr>) -> Vec<Enr> {
+    let mut by_node_id: HashMap<NodeId, Enr> = HashMap::new();
+    for enr in enrs {
+        by_node_id
+            .entry(enr.node_id())
+            .and_modify(|existing| keep_highest_seq(existing, enr.clone()))
+            .or_insert(enr);
+    }
+    by_node_id.into_values().collect()
+}
+
+/// Migrates the legacy monolithic `PersistedDht` blob to the keyed per-node layout, if one is
+/// still present under `DHT_DB_KEY`. This is a no-op (and does not touch any already-keyed
+/// entries) when no legacy blob exists, so it is safe to call on every `load_dht`/`prune_dht`
+/// invocation rather than only the first one.
+///
+/// Entries that already have a keyed record are left alone rather than overwritten: deleting the
+/// legacy blob is best-effort (a failure is logged but not fatal), so a later call here can find
+/// the blob still present after a node id has since been persisted for real with a fresher
+/// `last_seen`, and must not stamp it back down. Everything actually migrated is stamped with
+/// `last_seen = 0`, i.e. a generation older than any value `persist_dht` ever hands out (see
+/// `next_dht_seq_counter`), rather than routed through `persist_dht`'s "now" stamp: the legacy blob
+/// never tracked a `last_seen` of its own, and treating a migration as fresh evidence of activity
+/// would let long-dormant peers jump the queue and evict genuinely recently-seen ones the next time
+/// `prune_dht` runs.
+///
+/// The already-keyed check is done with a single scan of the `DhtEnrs` column rather than one
+/// point read per legacy entry, since a legacy blob can hold many ENRs.
+fn migrate_legacy_dht<E: EthSpec, Hot: ItemStore<E>, Cold: ItemStore<E>>(
+    store: &Arc<HotColdDB<E, Hot, Cold>>,
+    log: &Logger,
+) {
+    let legacy = match store.get_item::<PersistedDht>(&DHT_DB_KEY) {
+        Ok(Some(legacy)) => legacy,
+        Ok(None) => return,
+        Err(e) => {
+            warn!(log, "Failed to read legacy persisted DHT blob"; "error" => ?e);
+            return;
+        }
+    };
+
+    let mut already_keyed = std::collections::HashSet::new();
+    for res in store.hot_db.iter_column(DBColumn::DhtEnrs) {
+        match res {
+            Ok((key, _)) => {
+                already_keyed.insert(Hash256::from_slice(&key));
+            }
+            Err(e) => {
+                warn!(log, "Failed to scan existing keyed DHT records during migration"; "error" => ?e);
+                return;
+            }
+        }
+    }
+
+    let to_migrate: Vec<Enr> = dedupe_by_highest_seq(legacy.enrs)
+        .into_iter()
+        .filter(|enr| !already_keyed.contains(&node_id_key(enr)))
+        .collect();
+
+    if let Err(e) = store.do_atomically(persist_dht_ops(&to_migrate, 0)) {
+        warn!(log, "Failed to migrate legacy persisted DHT entries"; "error" => ?e);
+        return;
+    }
+    match store.hot_db.delete::<PersistedDht>(&DHT_DB_KEY) {
+        Ok(()) => debug!(log, "Migrated legacy persisted DHT blob"; "count" => to_migrate.len()),
+        Err(e) => warn!(log, "Failed to remove legacy persisted DHT blob"; "error" => ?e),
+    }
+}
+
+/// Loads all persisted ENRs, migrating the legacy single-blob layout to per-node keys if found.
+///
+/// If `expected_fork_digest` is `Some`, only ENRs advertising a compatible `eth2` fork digest (or
+/// no `eth2` field at all, i.e. unknown fork) are returned, so peers from a prior fork are not
+/// reloaded after an upgrade. Pass `None` to get the previous, unfiltered behaviour.
 pub fn load_dht<E: EthSpec, Hot: ItemStore<E>, Cold: ItemStore<E>>(
     store: Arc<HotColdDB<E, Hot, Cold>>,
+    expected_fork_digest: Option<[u8; 4]>,
+    log: &Logger,
 ) -> Vec<Enr> {
-    // Load DHT from store
-    match store.get_item(&DHT_DB_KEY) {
-        Ok(Some(p)) => {
-            let p: PersistedDht = p;
-            p.enrs
+    migrate_legacy_dht(&store, log);
+
+    let mut enrs = Vec::new();
+
+    for res in store.hot_db.iter_column(DBColumn::DhtEnrs) {
+        let (key, bytes) = match res {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!(log, "Failed to iterate DHT column entry"; "error" => ?e);
+                continue;
+            }
+        };
+
+        // Neither key holds an ENR record: the legacy blob was already migrated above (skipped
+        // defensively in case migration failed part-way), and the other is the write counter.
+        if key.as_slice() == DHT_DB_KEY.as_bytes() || key.as_slice() == dht_seq_db_key().as_bytes()
+        {
+            continue;
         }
-        _ => Vec::new(),
+
+        match PersistedEnr::from_store_bytes(&bytes) {
+            Ok(persisted) => enrs.push(persisted.enr),
+            Err(e) => warn!(log, "Skipping corrupt persisted ENR record"; "error" => ?e),
+        }
+    }
+
+    debug!(log, "Loaded persisted DHT"; "count" => enrs.len());
+
+    match expected_fork_digest {
+        Some(expected) => enrs
+            .into_iter()
+            .filter(|enr| match enr.eth2() {
+                // ENRs with no `eth2` field are of unknown fork and are kept regardless.
+                Err(_) => true,
+                Ok(fork_id) => fork_id.fork_digest == expected,
+            })
+            .collect(),
+        None => enrs,
     }
 }
 
-/// Attempt to persist the ENR's in the DHT to `self.store`.
+/// Loads persisted ENRs that advertise membership of at least one of `attnets`/`syncnets`,
+/// without requiring the discovery layer to pull the full set and filter it itself.
+pub fn load_dht_for_subnets<E: EthSpec, Hot: ItemStore<E>, Cold: ItemStore<E>>(
+    store: Arc<HotColdDB<E, Hot, Cold>>,
+    attnets: &[u64],
+    syncnets: &[u64],
+    log: &Logger,
+) -> Vec<Enr> {
+    load_dht(store, None, log)
+        .into_iter()
+        .filter(|enr| {
+            let matches_attnets = enr
+                .attestation_bitfield::<E>()
+                .map(|bitfield| {
+                    attnets
+                        .iter()
+                        .any(|&s| bitfield.get(s as usize).unwrap_or(false))
+                })
+                .unwrap_or(false);
+            let matches_syncnets = enr
+                .sync_committee_bitfield::<E>()
+                .map(|bitfield| {
+                    syncnets
+                        .iter()
+                        .any(|&s| bitfield.get(s as usize).unwrap_or(false))
+                })
+                .unwrap_or(false);
+            matches_attnets || matches_syncnets
+        })
+        .collect()
+}
+
+/// Builds the batched `StoreOp`s that write `enrs` under their per-node keys, each stamped with
+/// `last_seen`, without committing them. Callers that need to persist other state (e.g.
+/// blocks/states) in the same transaction can fold these into their own batch and commit
+/// everything via a single `do_atomically` call.
+pub fn persist_dht_ops<E: EthSpec>(enrs: &[Enr], last_seen: u64) -> Vec<StoreOp<'static, E>> {
+    enrs.iter()
+        .map(|enr| {
+            let item = PersistedEnr {
+                enr: enr.clone(),
+                last_seen,
+            };
+            StoreOp::KeyValueOp(item.as_kv_store_op(node_id_key(enr)))
+        })
+        .collect()
+}
+
+/// Persists `enrs` to the DHT, stamping every one of them with a freshly bumped `last_seen`.
+///
+/// Every call is proof that `enrs` are still known as of now, so `last_seen` advances for the
+/// whole set regardless of whether an entry's encoded bytes happen to be unchanged: skipping the
+/// stamp for stable entries would let a long-lived, still-connected peer look stale next to one
+/// whose ENR just happened to change, and `prune_dht`'s recency cap would evict the stable peer
+/// first -- the opposite of the intended LRU behaviour.
 pub fn persist_dht<E: EthSpec, Hot: ItemStore<E>, Cold: ItemStore<E>>(
     store: Arc<HotColdDB<E, Hot, Cold>>,
     enrs: Vec<Enr>,
 ) -> Result<(), store::Error> {
-    store.put_item(&DHT_DB_KEY, &PersistedDht { enrs })
+    if enrs.is_empty() {
+        return Ok(());
+    }
+
+    let last_seen = next_dht_seq_counter(&store)?;
+    let mut ops = persist_dht_ops(&enrs, last_seen);
+    ops.push(dht_seq_counter_op(last_seen));
+    store.do_atomically(ops)
+}
+
+/// Reads the current `DhtSeqCounter` and returns the next value to stamp onto freshly persisted
+/// ENRs, without writing it back. Pair with `dht_seq_counter_op` and `persist_dht_ops` to fold a
+/// DHT write into a caller's own atomic shutdown batch, exactly as `persist_dht` does internally.
+///
+/// The very first value handed out is `1`, not `0`: `migrate_legacy_dht` stamps entries recovered
+/// from the legacy blob with `last_seen = 0` because their true recency is unknown, and that stamp
+/// needs to rank strictly below every entry `persist_dht` has ever actually observed, including the
+/// first one. Starting the real counter at `0` would let a migrated, possibly long-dormant peer tie
+/// with (and potentially outlive, depending on hash/sort ordering) a peer persisted moments after
+/// node startup.
+pub fn next_dht_seq_counter<E: EthSpec, Hot: ItemStore<E>, Cold: ItemStore<E>>(
+    store: &HotColdDB<E, Hot, Cold>,
+) -> Result<u64, store::Error> {
+    Ok(store
+        .get_item::<DhtSeqCounter>(&dht_seq_db_key())?
+        .map_or(1, |counter| counter.0.saturating_add(1)))
+}
+
+/// Builds the `StoreOp` that commits `last_seen` as the new `DhtSeqCounter` value. Must be
+/// included in the same atomic batch as any `persist_dht_ops(_, last_seen)` built from the same
+/// counter value, or the counter will drift out of sync with what was actually written.
+pub fn dht_seq_counter_op<E: EthSpec>(last_seen: u64) -> StoreOp<'static, E> {
+    StoreOp::KeyValueOp(DhtSeqCounter(last_seen).as_kv_store_op(dht_seq_db_key()))
+}
+
+/// Garbage-collects the persisted ENR set: duplicate node ids are collapsed to the entry with
+/// the highest ENR `seq`, then the result is capped to the `max_entries` most recently-seen
+/// records (by the `last_seen` counter `persist_dht` stamps on every write). Any entry whose
+/// `last_seen` is at or above `min_seq_keep` is retained regardless of the cap, so a caller can
+/// protect a just-persisted batch from being immediately evicted. Survivors are already correctly
+/// persisted (only the dropped entries need deleting), so nothing is rewritten here: re-running
+/// them through `persist_dht` would restamp every surviving entry with the same fresh `last_seen`
+/// on every call, collapsing their relative recency the next time `prune_dht` runs. The kept set
+/// is returned for discovery to start from.
+///
+/// `prune_dht` does not require `load_dht` to have run first: it migrates any legacy monolithic
+/// blob itself before scanning, so ENRs aren't silently left out of the prune just because of
+/// call order.
+pub fn prune_dht<E: EthSpec, Hot: ItemStore<E>, Cold: ItemStore<E>>(
+    store: Arc<HotColdDB<E, Hot, Cold>>,
+    max_entries: usize,
+    min_seq_keep: u64,
+    log: &Logger,
+) -> Result<Vec<Enr>, store::Error> {
+    migrate_legacy_dht(&store, log);
+
+    let mut by_node_id: HashMap<NodeId, (Enr, u64)> = HashMap::new();
+
+    for res in store.hot_db.iter_column(DBColumn::DhtEnrs) {
+        let (key, bytes) = res?;
+        if key.as_slice() == DHT_DB_KEY.as_bytes() || key.as_slice() == dht_seq_db_key().as_bytes()
+        {
+            continue;
+        }
+        let persisted = match PersistedEnr::from_store_bytes(&bytes) {
+            Ok(persisted) => persisted,
+            Err(e) => {
+                warn!(log, "Skipping corrupt persisted ENR record during prune"; "error" => ?e);
+                continue;
+            }
+        };
+        by_node_id
+            .entry(persisted.enr.node_id())
+            .and_modify(|(existing_enr, existing_last_seen)| {
+                keep_highest_seq(existing_enr, persisted.enr.clone());
+                *existing_last_seen = (*existing_last_seen).max(persisted.last_seen);
+            })
+            .or_insert((persisted.enr, persisted.last_seen));
+    }
+
+    let mut entries: Vec<(Enr, u64)> = by_node_id.into_values().collect();
+    entries.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+
+    let (kept, dropped): (Vec<_>, Vec<_>) = entries
+        .into_iter()
+        .enumerate()
+        .partition(|(i, (_, last_seen))| *i < max_entries || *last_seen >= min_seq_keep);
+
+    for (_, (enr, _)) in dropped {
+        store.hot_db.delete::<PersistedEnr>(&node_id_key(&enr))?;
+    }
+
+    let kept_enrs: Vec<Enr> = kept.into_iter().map(|(_, (enr, _))| enr).collect();
+    debug!(log, "Pruned persisted DHT"; "count" => kept_enrs.len());
+    Ok(kept_enrs)
 }
 
-/// Attempts to clear any DHT entries.
+/// Attempts to clear any DHT entries, both keyed and the legacy monolithic blob, by deleting
+/// every key present in the `DhtEnrs` column rather than a single well-known key.
 pub fn clear_dht<E: EthSpec, Hot: ItemStore<E>, Cold: ItemStore<E>>(
     store: Arc<HotColdDB<E, Hot, Cold>>,
 ) -> Result<(), store::Error> {
-    store.hot_db.delete::<PersistedDht>(&DHT_DB_KEY)
+    for res in store.hot_db.iter_column_keys(DBColumn::DhtEnrs) {
+        let key = res?;
+        store.hot_db.key_delete(DBColumn::DhtEnrs, &key)?;
+    }
+    Ok(())
 }
 
-/// Wrapper around DHT for persistence to disk.
+/// Wrapper around a single ENR for keyed persistence to disk. `last_seen` is the value of the
+/// global `DhtSeqCounter` at the time this record was last written, used to evict the least
+/// recently seen entries when the set grows past `prune_dht`'s `max_entries`.
+pub struct PersistedEnr {
+    pub enr: Enr,
+    pub last_seen: u64,
+}
+
+impl StoreItem for PersistedEnr {
+    fn db_column() -> DBColumn {
+        DBColumn::DhtEnrs
+    }
+
+    fn as_store_bytes(&self) -> Vec<u8> {
+        let mut buffer = Vec::<u8>::new();
+        let header = alloy_rlp::Header {
+            list: true,
+            payload_length: self.last_seen.length() + self.enr.length(),
+        };
+        header.encode(&mut buffer);
+        self.last_seen.encode(&mut buffer);
+        self.enr.encode(&mut buffer);
+        buffer
+    }
+
+    fn from_store_bytes(bytes: &[u8]) -> Result<Self, StoreError> {
+        let buf = &mut &bytes[..];
+        alloy_rlp::Header::decode(buf)
+            .map_err(|e| StoreError::RlpError(format!("Failed to decode RLP header: {}", e)))?;
+        let last_seen = u64::decode(buf)
+            .map_err(|e| StoreError::RlpError(format!("Failed to decode last_seen: {}", e)))?;
+        let enr = Enr::decode(buf)
+            .map_err(|e| StoreError::RlpError(format!("Failed to decode RLP: {}", e)))?;
+        Ok(PersistedEnr { enr, last_seen })
+    }
+}
+
+/// Holds the monotonically increasing counter stamped onto every ENR written by `persist_dht`.
+struct DhtSeqCounter(u64);
+
+impl StoreItem for DhtSeqCounter {
+    fn db_column() -> DBColumn {
+        DBColumn::DhtEnrs
+    }
+
+    fn as_store_bytes(&self) -> Vec<u8> {
+        self.0.to_le_bytes().to_vec()
+    }
+
+    fn from_store_bytes(bytes: &[u8]) -> Result<Self, StoreError> {
+        let bytes: [u8; 8] = bytes
+            .try_into()
+            .map_err(|_| StoreError::RlpError("Invalid DHT sequence counter length".to_string()))?;
+        Ok(DhtSeqCounter(u64::from_le_bytes(bytes)))
+    }
+}
+
+/// Wrapper around the legacy, monolithic DHT blob, kept only to decode old databases.
 pub struct PersistedDht {
     pub enrs: Vec<Enr>,
 }
@@ -70,6 +418,7 @@ impl StoreItem for PersistedDht {
 mod tests {
     use super::*;
     use sloggers::{null::NullLoggerBuilder, Build};
+    use ssz::Encode;
     use std::str::FromStr;
     use store::config::StoreConfig;
     use store::MemoryStore;
@@ -90,4 +439,371 @@ mod tests {
         let dht: PersistedDht = store.get_item(&DHT_DB_KEY).unwrap().unwrap();
         assert_eq!(dht.enrs, enrs);
     }
+
+    type TestStore =
+        HotColdDB<MinimalEthSpec, MemoryStore<MinimalEthSpec>, MemoryStore<MinimalEthSpec>>;
+
+    fn test_store() -> Arc<TestStore> {
+        let log = NullLoggerBuilder.build().unwrap();
+        Arc::new(
+            HotColdDB::open_ephemeral(StoreConfig::default(), ChainSpec::minimal().into(), log)
+                .unwrap(),
+        )
+    }
+
+    fn test_enr() -> Enr {
+        Enr::from_str("enr:-IS4QHCYrYZbAKWCBRlAy5zzaDZXJBGkcnh4MHcBFZntXNFrdvJjX04jRzjzCBOonrkTfj499SZuOh8R33Ls8RRcy5wBgmlkgnY0gmlwhH8AAAGJc2VjcDI1NmsxoQPKY0yuDUmstAHYpMa2_oxVtw0RW_QAdpzBQA8yWM0xOIN1ZHCCdl8").unwrap()
+    }
+
+    #[test]
+    fn persist_and_load_dht_round_trips_keyed_enrs() {
+        let log = NullLoggerBuilder.build().unwrap();
+        let store = test_store();
+        let enr = test_enr();
+
+        persist_dht(store.clone(), vec![enr.clone()]).unwrap();
+        let loaded = load_dht(store, None, &log);
+
+        assert_eq!(loaded, vec![enr]);
+    }
+
+    #[test]
+    fn load_dht_skips_corrupt_records() {
+        let log = NullLoggerBuilder.build().unwrap();
+        let store = test_store();
+        let enr = test_enr();
+
+        persist_dht(store.clone(), vec![enr.clone()]).unwrap();
+        // Add a bogus node-id key with unparseable bytes; it should be skipped rather than
+        // aborting the whole load.
+        store
+            .hot_db
+            .put_bytes(
+                DBColumn::DhtEnrs,
+                Hash256::from([1u8; 32]).as_bytes(),
+                b"not rlp",
+            )
+            .unwrap();
+
+        let loaded = load_dht(store, None, &log);
+        assert_eq!(loaded, vec![enr]);
+    }
+
+    #[test]
+    fn load_dht_migrates_legacy_blob() {
+        let log = NullLoggerBuilder.build().unwrap();
+        let store = test_store();
+        let enr = test_enr();
+
+        // Simulate a pre-upgrade database: only the monolithic blob exists.
+        store
+            .put_item(
+                &DHT_DB_KEY,
+                &PersistedDht {
+                    enrs: vec![enr.clone()],
+                },
+            )
+            .unwrap();
+
+        let loaded = load_dht(store.clone(), None, &log);
+        assert_eq!(loaded, vec![enr.clone()]);
+
+        // The legacy blob should be gone and the ENR now live under its own keyed record.
+        assert!(store
+            .get_item::<PersistedDht>(&DHT_DB_KEY)
+            .unwrap()
+            .is_none());
+        let keyed: PersistedEnr = store.get_item(&node_id_key(&enr)).unwrap().unwrap();
+        assert_eq!(keyed.enr, enr);
+    }
+
+    #[test]
+    fn persist_dht_refreshes_last_seen_on_every_call() {
+        let store = test_store();
+        let enr = test_enr();
+
+        persist_dht(store.clone(), vec![enr.clone()]).unwrap();
+        let first: PersistedEnr = store.get_item(&node_id_key(&enr)).unwrap().unwrap();
+
+        // Persisting the exact same set again is proof the peer is still known, so `last_seen`
+        // must advance even though the ENR's own bytes are unchanged.
+        persist_dht(store.clone(), vec![enr.clone()]).unwrap();
+        let second: PersistedEnr = store.get_item(&node_id_key(&enr)).unwrap().unwrap();
+
+        assert!(second.last_seen > first.last_seen);
+    }
+
+    #[test]
+    fn persist_dht_ops_can_be_folded_into_an_external_batch() {
+        let log = NullLoggerBuilder.build().unwrap();
+        let store = test_store();
+        let enr = test_enr();
+
+        // Mirrors how a caller persisting other shutdown state would fold the DHT write into its
+        // own atomic batch, without calling `persist_dht` directly.
+        let last_seen = next_dht_seq_counter(&store).unwrap();
+        let mut ops = persist_dht_ops(&[enr.clone()], last_seen);
+        ops.push(dht_seq_counter_op(last_seen));
+        store.do_atomically(ops).unwrap();
+
+        let loaded = load_dht(store, None, &log);
+        assert_eq!(loaded, vec![enr]);
+    }
+
+    fn enr_with_fork_digest(fork_digest: [u8; 4]) -> Enr {
+        let key = lighthouse_network::discv5::enr::CombinedKey::generate_secp256k1();
+        let fork_id = types::EnrForkId {
+            fork_digest,
+            next_fork_version: [0; 4],
+            next_fork_epoch: u64::MAX,
+        };
+        lighthouse_network::discv5::enr::EnrBuilder::new("v4")
+            .add_value("eth2", &fork_id.as_ssz_bytes())
+            .build(&key)
+            .unwrap()
+    }
+
+    #[test]
+    fn load_dht_filters_by_fork_digest() {
+        let log = NullLoggerBuilder.build().unwrap();
+        let store = test_store();
+        let matching = enr_with_fork_digest([1, 2, 3, 4]);
+        let stale = enr_with_fork_digest([9, 9, 9, 9]);
+        let unknown = test_enr();
+
+        persist_dht(
+            store.clone(),
+            vec![matching.clone(), stale.clone(), unknown.clone()],
+        )
+        .unwrap();
+
+        let mut loaded = load_dht(store, Some([1, 2, 3, 4]), &log);
+        loaded.sort_by_key(|enr| enr.seq());
+
+        // The stale-fork ENR is dropped; the matching and fork-unknown ENRs are kept.
+        assert_eq!(loaded.len(), 2);
+        assert!(loaded.contains(&matching));
+        assert!(loaded.contains(&unknown));
+        assert!(!loaded.contains(&stale));
+    }
+
+    fn enr_with_attnet(bit: usize) -> Enr {
+        let key = lighthouse_network::discv5::enr::CombinedKey::generate_secp256k1();
+        let mut bitfield: types::EnrAttestationBitfield<MinimalEthSpec> = Default::default();
+        bitfield.set(bit, true).unwrap();
+        lighthouse_network::discv5::enr::EnrBuilder::new("v4")
+            .add_value("attnets", &bitfield.as_ssz_bytes())
+            .build(&key)
+            .unwrap()
+    }
+
+    #[test]
+    fn load_dht_for_subnets_filters_by_attnet_membership() {
+        let log = NullLoggerBuilder.build().unwrap();
+        let store = test_store();
+        let wanted = enr_with_attnet(2);
+        let unwanted = enr_with_attnet(5);
+        let no_bitfield = test_enr();
+
+        persist_dht(
+            store.clone(),
+            vec![wanted.clone(), unwanted.clone(), no_bitfield.clone()],
+        )
+        .unwrap();
+
+        let loaded = load_dht_for_subnets::<MinimalEthSpec, _, _>(store, &[2], &[], &log);
+
+        // Only the ENR advertising the requested attnet is returned; ENRs advertising other
+        // subnets or no bitfield at all are excluded.
+        assert_eq!(loaded, vec![wanted]);
+    }
+
+    #[test]
+    fn prune_dht_caps_to_max_entries_by_recency() {
+        let log = NullLoggerBuilder.build().unwrap();
+        let store = test_store();
+
+        // Persist three ENRs one at a time so each gets a strictly increasing `last_seen`.
+        let mut enrs = Vec::new();
+        for i in 0..3 {
+            let enr = enr_with_fork_digest([i, i, i, i]);
+            persist_dht(store.clone(), vec![enr.clone()]).unwrap();
+            enrs.push(enr);
+        }
+
+        // min_seq_keep = u64::MAX protects nothing (no entry's last_seen can reach it), so the
+        // cap is the only thing deciding what survives.
+        let kept = prune_dht(store, 2, u64::MAX, &log).unwrap();
+
+        // Only the two most-recently-persisted ENRs survive.
+        assert_eq!(kept.len(), 2);
+        assert!(kept.contains(&enrs[1]));
+        assert!(kept.contains(&enrs[2]));
+        assert!(!kept.contains(&enrs[0]));
+    }
+
+    #[test]
+    fn prune_dht_protects_entries_above_min_seq_keep() {
+        let log = NullLoggerBuilder.build().unwrap();
+        let store = test_store();
+
+        let mut enrs = Vec::new();
+        for i in 0..3 {
+            let enr = enr_with_fork_digest([i, i, i, i]);
+            persist_dht(store.clone(), vec![enr.clone()]).unwrap();
+            enrs.push(enr);
+        }
+
+        // A cap of 1 would normally drop the two oldest, but min_seq_keep=0 protects everything
+        // that was ever written (every `last_seen` here is >= 0).
+        let kept = prune_dht(store, 1, 0, &log).unwrap();
+        assert_eq!(kept.len(), 3);
+    }
+
+    #[test]
+    fn prune_dht_protects_entries_above_a_nonzero_min_seq_keep() {
+        let log = NullLoggerBuilder.build().unwrap();
+        let store = test_store();
+
+        let mut enrs = Vec::new();
+        for i in 0..3 {
+            let enr = enr_with_fork_digest([i, i, i, i]);
+            persist_dht(store.clone(), vec![enr.clone()]).unwrap();
+            enrs.push(enr);
+        }
+
+        // last_seen values are 1, 2, 3 (the counter starts at 1, reserving 0 for migrated legacy
+        // entries). A cap of 0 would normally drop every entry, but min_seq_keep = 2 sits strictly
+        // between them, protecting only the two seen at or after that point.
+        let kept = prune_dht(store, 0, 2, &log).unwrap();
+
+        assert_eq!(kept.len(), 2);
+        assert!(kept.contains(&enrs[1]));
+        assert!(kept.contains(&enrs[2]));
+        assert!(!kept.contains(&enrs[0]));
+    }
+
+    #[test]
+    fn prune_dht_preserves_recency_across_repeated_calls() {
+        let log = NullLoggerBuilder.build().unwrap();
+        let store = test_store();
+
+        let mut enrs = Vec::new();
+        for i in 0..3 {
+            let enr = enr_with_fork_digest([i, i, i, i]);
+            persist_dht(store.clone(), vec![enr.clone()]).unwrap();
+            enrs.push(enr);
+        }
+
+        // A prune that keeps everyone must not disturb relative recency: a second, tighter prune
+        // should still evict the least-recently-seen entry first rather than an arbitrary one.
+        // min_seq_keep = u64::MAX protects nothing, so both calls rely purely on the cap.
+        prune_dht(store.clone(), 3, u64::MAX, &log).unwrap();
+        let kept = prune_dht(store, 2, u64::MAX, &log).unwrap();
+
+        assert_eq!(kept.len(), 2);
+        assert!(kept.contains(&enrs[1]));
+        assert!(kept.contains(&enrs[2]));
+        assert!(!kept.contains(&enrs[0]));
+    }
+
+    #[test]
+    fn migrated_legacy_entries_do_not_outrank_recently_persisted_peers() {
+        let log = NullLoggerBuilder.build().unwrap();
+        let store = test_store();
+
+        let recent = enr_with_fork_digest([1, 1, 1, 1]);
+        persist_dht(store.clone(), vec![recent.clone()]).unwrap();
+
+        let dormant = test_enr();
+        store
+            .put_item(
+                &DHT_DB_KEY,
+                &PersistedDht {
+                    enrs: vec![dormant],
+                },
+            )
+            .unwrap();
+
+        // The legacy entry migrates with last_seen = 0, not "now", so it must not jump ahead of
+        // the already-persisted, more recently-seen peer when only one of them can survive.
+        // min_seq_keep = u64::MAX protects nothing, so the cap alone decides the outcome.
+        let kept = prune_dht(store, 1, u64::MAX, &log).unwrap();
+        assert_eq!(kept, vec![recent]);
+    }
+
+    #[test]
+    fn migrate_legacy_dht_does_not_clobber_an_already_keyed_entry() {
+        let log = NullLoggerBuilder.build().unwrap();
+        let store = test_store();
+        let enr = test_enr();
+
+        // Simulate a previous migration that wrote the keyed record but failed to delete the
+        // legacy blob: the node id is already persisted for real, with a non-zero `last_seen`.
+        persist_dht(store.clone(), vec![enr.clone()]).unwrap();
+        persist_dht(store.clone(), vec![enr.clone()]).unwrap();
+        let before: PersistedEnr = store.get_item(&node_id_key(&enr)).unwrap().unwrap();
+        assert!(before.last_seen > 0);
+
+        store
+            .put_item(
+                &DHT_DB_KEY,
+                &PersistedDht {
+                    enrs: vec![enr.clone()],
+                },
+            )
+            .unwrap();
+
+        // Migrating again must not stamp the already-keyed, fresher entry back down to 0.
+        load_dht(store.clone(), None, &log);
+        let after: PersistedEnr = store.get_item(&node_id_key(&enr)).unwrap().unwrap();
+        assert_eq!(before.last_seen, after.last_seen);
+    }
+
+    #[test]
+    fn migrate_legacy_dht_dedupes_duplicate_node_ids_by_highest_seq() {
+        let log = NullLoggerBuilder.build().unwrap();
+        let store = test_store();
+
+        let key = lighthouse_network::discv5::enr::CombinedKey::generate_secp256k1();
+        let stale = lighthouse_network::discv5::enr::EnrBuilder::new("v4")
+            .build(&key)
+            .unwrap();
+        let mut fresh = stale.clone();
+        fresh.set_seq(stale.seq() + 1, &key).unwrap();
+
+        // A corrupt/old legacy blob that somehow has two records for the same node id, out of
+        // `seq` order. Only the higher-`seq` one should survive migration.
+        store
+            .put_item(
+                &DHT_DB_KEY,
+                &PersistedDht {
+                    enrs: vec![stale, fresh.clone()],
+                },
+            )
+            .unwrap();
+
+        let loaded = load_dht(store, None, &log);
+        assert_eq!(loaded, vec![fresh]);
+    }
+
+    #[test]
+    fn prune_dht_migrates_legacy_blob_before_scanning() {
+        let log = NullLoggerBuilder.build().unwrap();
+        let store = test_store();
+        let enr = test_enr();
+
+        store
+            .put_item(
+                &DHT_DB_KEY,
+                &PersistedDht {
+                    enrs: vec![enr.clone()],
+                },
+            )
+            .unwrap();
+
+        // prune_dht is called before load_dht ever runs; the legacy ENR must still surface.
+        let kept = prune_dht(store, 10, 0, &log).unwrap();
+        assert_eq!(kept, vec![enr]);
+    }
 }